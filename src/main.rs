@@ -8,6 +8,8 @@ use piston_window::*;
 use rand::prelude::*;
 // control memory allocation (alloc is the UNIX system call for requesting mem from the allocator)
 use std::alloc::{GlobalAlloc, System, Layout};
+// lock-free counters the allocator updates from `alloc`, read back once per turn
+use std::sync::atomic::{AtomicU64, Ordering};
 // for access to the system clock
 use std::time::Instant;
 
@@ -15,20 +17,20 @@ use std::time::Instant;
 #[global_allocator]
 static ALLOCATOR: ReportingAllocator = ReportingAllocator;
 
-struct ReportingAllocator;
+static ALLOC_STATS: AllocStats = AllocStats::new();
 
-// prints time taken for each allocation to STDOUT as the program runs
-// This gives us an accurate indication of the time taken for dynamic memory allocation
+struct ReportingAllocator;
 
+// records every allocation's size and timing into `ALLOC_STATS` with relaxed
+// atomics, rather than a per-call eprintln! that both floods stderr and
+// distorts the timing it's trying to measure at thousands of particles/frame
 unsafe impl GlobalAlloc for ReportingAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let start = Instant::now();
         let ptr = System.alloc(layout); // default the actual allocation to the system's default memory allocator
-        let end = Instant::now();
-        let time_taken = end - start;
-        let bytes_requested = layout.size();
+        let time_taken = start.elapsed();
 
-        eprintln!("Bytes requested: {}\t Time: {}", bytes_requested, time_taken.as_nanos());
+        ALLOC_STATS.record(layout.size() as u64, time_taken.as_nanos() as u64);
         ptr // return raw pointer
     }
 
@@ -37,60 +39,297 @@ unsafe impl GlobalAlloc for ReportingAllocator {
     }
 }
 
+// aggregate allocation statistics, updated lock-free from `alloc` and read
+// back (and reset) once per turn
+struct AllocStats {
+    count: AtomicU64,
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    bucket_le16: AtomicU64,
+    bucket_le64: AtomicU64,
+    bucket_le256: AtomicU64,
+    bucket_le1024: AtomicU64,
+    bucket_gt1024: AtomicU64,
+}
+
+// a point-in-time read of `AllocStats`, taken via `AllocStats::snapshot_and_reset`
+struct AllocSnapshot {
+    count: u64,
+    bytes: u64,
+    nanos: u64,
+    max_nanos: u64,
+    buckets: [u64; 5], // <=16, <=64, <=256, <=1024, >1024 bytes
+}
+
+impl AllocStats {
+    const fn new() -> AllocStats {
+        AllocStats {
+            count: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            max_nanos: AtomicU64::new(0),
+            bucket_le16: AtomicU64::new(0),
+            bucket_le64: AtomicU64::new(0),
+            bucket_le256: AtomicU64::new(0),
+            bucket_le1024: AtomicU64::new(0),
+            bucket_gt1024: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, bytes: u64, nanos: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+
+        let bucket = match bytes {
+            0..=16 => &self.bucket_le16,
+            17..=64 => &self.bucket_le64,
+            65..=256 => &self.bucket_le256,
+            257..=1024 => &self.bucket_le1024,
+            _ => &self.bucket_gt1024,
+        };
+        bucket.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // reads every counter and zeroes it atomically, so the next snapshot
+    // only reflects allocations made since this call
+    fn snapshot_and_reset(&self) -> AllocSnapshot {
+        AllocSnapshot {
+            count: self.count.swap(0, Ordering::Relaxed),
+            bytes: self.bytes.swap(0, Ordering::Relaxed),
+            nanos: self.nanos.swap(0, Ordering::Relaxed),
+            max_nanos: self.max_nanos.swap(0, Ordering::Relaxed),
+            buckets: [
+                self.bucket_le16.swap(0, Ordering::Relaxed),
+                self.bucket_le64.swap(0, Ordering::Relaxed),
+                self.bucket_le256.swap(0, Ordering::Relaxed),
+                self.bucket_le1024.swap(0, Ordering::Relaxed),
+                self.bucket_gt1024.swap(0, Ordering::Relaxed),
+            ],
+        }
+    }
+}
+
+// smallest lifetime a particle can be spawned with, so a sampled negative
+// or zero lifetime can't divide by zero or flip the sign of age/lifetime
+const MIN_LIFETIME: f64 = 1.0 / 60.0;
+
+// owns every tunable spawn parameter so the rest of the crate no longer
+// hard-codes where particles appear, how they move, or how long they live
+struct Emitter {
+    origin: Vec2d<f64>,
+    spawn_area: Vec2d<f64>, // width/height of the region around origin particles can spawn within
+    spawn_rate: f64,        // particles emitted per second
+    spawn_timer: f64,       // seconds accumulated since the last spawn
+    direction: Vec2d<f64>,  // base direction of travel (need not be normalized)
+    angular_spread: f64,    // total angle (radians) of random spread centered on direction's angle
+    velocity: f64,          // base speed along direction
+    velocity_spread: f64,   // +/- random variation applied to velocity
+    lifetime: f64,
+    lifetime_range: f64, // +/- random variation applied to lifetime
+    size: f64,
+    size_range: f64, // +/- random variation applied to size
+    color_start: [f32; 4],
+    color_mid: [f32; 4],
+    color_end: [f32; 4],
+    scale_start: f64, // size multiplier at birth
+    scale_end: f64,   // size multiplier at death
+}
+
+impl Emitter {
+    // the original bottom-of-screen fountain, expressed as emitter parameters
+    fn fountain(width: f64, height: f64) -> Emitter {
+        Emitter {
+            origin: [width / 2.0, height].into(),
+            spawn_area: [width, 0.0].into(),
+            spawn_rate: 120.0,
+            spawn_timer: 0.0,
+            direction: [0.0, -1.0].into(),
+            angular_spread: 0.0,
+            velocity: 90.0,
+            velocity_spread: 30.0,
+            lifetime: 3.3,
+            lifetime_range: 0.8,
+            size: 4.0,
+            size_range: 0.0,
+            color_start: [1.0, 1.0, 1.0, 1.0],
+            color_mid: [1.0, 1.0, 1.0, 0.6],
+            color_end: [1.0, 1.0, 1.0, 0.0],
+            scale_start: 1.0,
+            scale_end: 1.0,
+        }
+    }
+
+    // a transient, one-shot cluster spawned at `origin`: particles fly
+    // radially outward in every direction rather than drifting from a
+    // continuous source
+    fn burst(origin: Vec2d<f64>) -> Emitter {
+        Emitter {
+            origin,
+            spawn_area: [0.0, 0.0].into(),
+            spawn_rate: 1.0, // unused: burst particles are spawned directly, not accumulated via a timer
+            spawn_timer: 0.0,
+            direction: [1.0, 0.0].into(),
+            angular_spread: 2.0 * std::f64::consts::PI,
+            velocity: 150.0,
+            velocity_spread: 100.0,
+            lifetime: 0.8,
+            lifetime_range: 0.3,
+            size: 3.0,
+            size_range: 1.0,
+            color_start: [1.0, 0.8, 0.3, 1.0],
+            color_mid: [1.0, 0.4, 0.1, 0.8],
+            color_end: [0.6, 0.1, 0.1, 0.0],
+            scale_start: 1.0,
+            scale_end: 0.2,
+        }
+    }
+
+    // advances the spawn timer by `dt` seconds and returns how many
+    // particles should be emitted this update
+    fn particles_to_spawn(&mut self, dt: f64) -> u32 {
+        self.spawn_timer += dt;
+        let interval = 1.0 / self.spawn_rate;
+        let mut n = 0;
+        while self.spawn_timer >= interval {
+            self.spawn_timer -= interval;
+            n += 1;
+        }
+        n
+    }
+}
+
+// a global force applied to every particle's acceleration each update,
+// evaluated fresh every frame based on the particle's current position
+enum ForceField {
+    // a uniform acceleration, e.g. gravity
+    Directional(Vec2d<f64>),
+    // pulls particles toward (positive strength) or pushes them away from
+    // (negative strength) `center`, falling off as 1/distance^falloff
+    Radial { center: Vec2d<f64>, strength: f64, falloff: f64 },
+}
+
+impl ForceField {
+    fn at(&self, position: Vec2d<f64>) -> Vec2d<f64> {
+        match self {
+            ForceField::Directional(acceleration) => *acceleration,
+            ForceField::Radial { center, strength, falloff } => {
+                let delta = [center[0] - position[0], center[1] - position[1]];
+                let dist = (delta[0] * delta[0] + delta[1] * delta[1]).sqrt().max(1.0);
+                let magnitude = strength / dist.powf(*falloff);
+                [delta[0] / dist * magnitude, delta[1] / dist * magnitude].into()
+            }
+        }
+    }
+}
+
+// particles a fresh World preallocates room for, so the initial burst of
+// spawns doesn't force the backing Vec to reallocate
+const PARTICLE_POOL_CAPACITY: usize = 4096;
+
 // data useful for lifetime of program
 struct World {
     current_turn: u64,
-    particles: Vec<Box<Particle>>, // vector of heaped particles
-    height: f64,
-    width: f64,
-    rng: ThreadRng,
+    // particles are stored inline rather than boxed, and dead ones are
+    // reclaimed with swap_remove so spawning/culling never shifts the Vec
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    forces: Vec<ForceField>,
+    last_update: Instant,
+}
+
+// linearly interpolates a single channel from `a` to `b`
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// linearly interpolates every channel of a color
+fn lerp_color(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [lerp(a[0], b[0], t), lerp(a[1], b[1], t), lerp(a[2], b[2], t), lerp(a[3], b[3], t)]
 }
 
 // object in 2d space
 struct Particle {
+    size: f64, // base width/height, before scale_start/scale_end are applied
     height: f64,
     width: f64,
     position: Vec2d<f64>,
     velocity: Vec2d<f64>,
     acceleration: Vec2d<f64>,
     color: [f32; 4],
+    age: f64,
+    lifetime: f64,
+    color_start: [f32; 4],
+    color_mid: [f32; 4],
+    color_end: [f32; 4],
+    scale_start: f64,
+    scale_end: f64,
 }
 
 impl Particle {
-    fn new(world: &World) -> Particle {
+    fn new(emitter: &mut Emitter) -> Particle {
         let mut rng = thread_rng();
-        // random x axis spawn point
-        // y axis always spawn in the same place
-        let x = rng.gen_range((0.0)..=world.width);
-        let y = world.height;
 
-        let x_velocity = 0.0;
+        let x = emitter.origin[0] + rng.gen_range(-emitter.spawn_area[0] / 2.0..=emitter.spawn_area[0] / 2.0);
+        let y = emitter.origin[1] + rng.gen_range(-emitter.spawn_area[1] / 2.0..=emitter.spawn_area[1] / 2.0);
 
-        // rise vertically over time
-        let y_velocity = rng.gen_range(-2.0..0.0);
+        let base_angle = emitter.direction[1].atan2(emitter.direction[0]);
+        let angle = base_angle + rng.gen_range(-emitter.angular_spread / 2.0..=emitter.angular_spread / 2.0);
+        let speed = emitter.velocity + rng.gen_range(-emitter.velocity_spread..=emitter.velocity_spread);
+        let velocity: Vec2d<f64> = [angle.cos() * speed, angle.sin() * speed].into();
 
-        let x_acceleration = 0.0;
-
-        // increase speed of rise over time
-        let y_acceleration = rng.gen_range(0.0..0.15);
+        let size = emitter.size + rng.gen_range(-emitter.size_range..=emitter.size_range);
+        let lifetime = (emitter.lifetime + rng.gen_range(-emitter.lifetime_range..=emitter.lifetime_range)).max(MIN_LIFETIME);
 
         Particle {
-            height: 4.0,
-            width: 4.0,
+            size,
+            height: size * emitter.scale_start,
+            width: size * emitter.scale_start,
             position: [x, y].into(), // into() converts arrays of type [f64; 2] into Vec2d
-            velocity: [x_velocity, y_velocity].into(),
-            acceleration: [x_acceleration, y_acceleration].into(),
-            color: [1.0, 1.0, 1.0, 0.99], // fully white with a tiny amount of transparency
+            velocity,
+            acceleration: [0.0, 0.0].into(),
+            color: emitter.color_start,
+            age: 0.0,
+            lifetime,
+            color_start: emitter.color_start,
+            color_mid: emitter.color_mid,
+            color_end: emitter.color_end,
+            scale_start: emitter.scale_start,
+            scale_end: emitter.scale_end,
         }
     }
 
-    fn update(&mut self) {
-        // move particle to next position
-        self.velocity = add(self.velocity, self.acceleration);
-        self.position = add(self.position, self.velocity);
-        // slows down the particles rate of increase as it travels across the screen
-        self.acceleration = mul_scalar(self.acceleration, 0.7);
-        self.color[3] *= 0.995; // slowly make more transparent
+    // accumulates a force's contribution to this frame's acceleration;
+    // call once per active force field before `update`
+    fn apply_force(&mut self, force: Vec2d<f64>) {
+        self.acceleration = add(self.acceleration, force);
+    }
+
+    fn update(&mut self, dt: f64) {
+        // move particle to next position, scaled by elapsed real time
+        self.velocity = add(self.velocity, mul_scalar(self.acceleration, dt));
+        self.position = add(self.position, mul_scalar(self.velocity, dt));
+        // acceleration is recomputed from force fields every frame
+        self.acceleration = [0.0, 0.0].into();
+
+        self.age += dt;
+        let t = (self.age / self.lifetime).min(1.0) as f32;
+
+        self.color = if t <= 0.5 {
+            lerp_color(self.color_start, self.color_mid, t * 2.0)
+        } else {
+            lerp_color(self.color_mid, self.color_end, (t - 0.5) * 2.0)
+        };
+
+        let scale = lerp(self.scale_start as f32, self.scale_end as f32, t) as f64;
+        self.height = self.size * scale;
+        self.width = self.size * scale;
+    }
+
+    fn is_alive(&self) -> bool {
+        self.age < self.lifetime
     }
 }
 
@@ -98,63 +337,86 @@ impl World {
     fn new(width: f64, height: f64) -> World {
         World {
             current_turn: 0,
-            // use Box<Particle> instead of Particle to incur an extra memory allocation when every particle is created
-            particles: Vec::<Box<Particle>>::new(),
-            height: height,
-            width: width,
-            rng: thread_rng(),
+            particles: Vec::with_capacity(PARTICLE_POOL_CAPACITY),
+            emitters: vec![Emitter::fountain(width, height)],
+            forces: vec![
+                // pulls particles down so the fountain's upward launch arcs
+                // and falls back, rather than accelerating off-screen forever
+                ForceField::Directional([0.0, 40.0].into()),
+                // gentle outward push from screen center, so the stream
+                // fans out horizontally instead of rising in a straight line
+                ForceField::Radial { center: [width / 2.0, height / 2.0].into(), strength: -6000.0, falloff: 1.0 },
+            ],
+            last_update: Instant::now(),
         }
     }
 
     fn add_shapes(&mut self, n: i32) {
         for _ in 0..n.abs() {
-            // create a particle as a local var on the stack
-            let particle = Particle::new(&self);
-            // take ownership of particle, move its data to the heap and create a reference to that data on the stack
-            let boxed_particle = Box::new(particle);
-            // push the reference into self.particles
-            self.particles.push(boxed_particle);
+            let particle = Particle::new(&mut self.emitters[0]);
+            self.particles.push(particle);
         }
     }
 
-    fn remove_shapes(&mut self, n: i32) {
-        for _ in 0..n.abs() {
-            let mut to_delete = None;
-            // iter.enumerate() gives us the index too in this tuple
-
-            // for n iterations, remove the first particle that's invisibile.
-            // If there are no invisible particles, then remove the oldest
-            for (i, particle) in self.particles.iter().enumerate() {
-                if particle.color[3] < 0.02 { // 0.02 is basically invisible
-                    to_delete = Some(i);
-                }
-                break;
-            }
-
-            if let Some(i) = to_delete {
-                self.particles.remove(i);
-            } else {
-                self.particles.remove(0);
-            };
+    // spawns a one-shot radial burst of `n` particles at `origin`
+    fn spawn_burst(&mut self, origin: Vec2d<f64>, n: u32) {
+        let mut emitter = Emitter::burst(origin);
+        for _ in 0..n {
+            let particle = Particle::new(&mut emitter);
+            self.particles.push(particle);
         }
     }
 
     fn update(&mut self) {
-        let n = self.rng.gen_range(-3..=3); // random int between -3 and 3, inclusive
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
 
-        if n > 0 {
-            self.add_shapes(n);
-        } else {
-            self.remove_shapes(n);
+        for emitter in &mut self.emitters {
+            let spawned = emitter.particles_to_spawn(dt);
+            for _ in 0..spawned {
+                let particle = Particle::new(emitter);
+                self.particles.push(particle);
+            }
         }
 
-        self.particles.shrink_to_fit();
-
         for shape in &mut self.particles {
-            shape.update();
+            for force in &self.forces {
+                shape.apply_force(force.at(shape.position));
+            }
+            shape.update(dt);
+        }
+
+        // a particle is removed exactly when it has aged past its lifetime;
+        // swap_remove reclaims the slot in O(1) instead of shifting the Vec
+        let mut i = 0;
+        while i < self.particles.len() {
+            if self.particles[i].is_alive() {
+                i += 1;
+            } else {
+                self.particles.swap_remove(i);
+            }
         }
 
         self.current_turn += 1;
+
+        Self::report_alloc_stats();
+    }
+
+    // prints one compact summary line of the allocations made since the
+    // last turn, instead of the per-call spam `ReportingAllocator` used to emit;
+    // prints nothing on a turn with no allocations
+    fn report_alloc_stats() {
+        let snapshot = ALLOC_STATS.snapshot_and_reset();
+        if snapshot.count == 0 {
+            return;
+        }
+
+        let mean_nanos = snapshot.nanos / snapshot.count;
+        eprintln!(
+            "allocs/turn: {}\tbytes/turn: {}\tmean: {}ns\tmax: {}ns\tbuckets[<=16,<=64,<=256,<=1024,>1024]: {:?}",
+            snapshot.count, snapshot.bytes, mean_nanos, snapshot.max_nanos, snapshot.buckets
+        );
     }
 }
 
@@ -167,11 +429,23 @@ fn main() {
     .exit_on_esc(true)
     .build()
     .expect("Could not create a window.");
-    
+
     let mut world = World::new(width, height);
     world.add_shapes(1000);
 
+    // particles/burst for each left click, tracked from the most recent cursor position
+    const BURST_SIZE: u32 = 150;
+    let mut cursor = [0.0, 0.0];
+
     while let Some(event) = window.next() {
+        if let Some(pos) = event.mouse_cursor_args() {
+            cursor = pos;
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+            world.spawn_burst(cursor.into(), BURST_SIZE);
+        }
+
         world.update();
 
         window.draw_2d(&event, |ctx, renderer, _device| {